@@ -0,0 +1,132 @@
+use std::io;
+use std::io::BufReader;
+
+// default chunk-size bounds; see the request for the normalized-chunking rationale below.
+const MIN_CHUNK_SIZE: usize = 2 * 1024;
+const AVG_CHUNK_SIZE: usize = 8 * 1024;
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+// two masks, switched at the target average: below it we check fewer fingerprint bits (a
+// "smaller" mask, a boundary about once every 8 KiB), past it we check more bits (a "larger"
+// mask, about once every 32 KiB), so a chunk that outgrows the average tends to run on towards
+// `max_size`.
+const MASK_SMALL: u64 = (1 << 13) - 1;
+const MASK_LARGE: u64 = (1 << 15) - 1;
+
+const fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = splitmix64(i as u64 + 1);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+/// A content-defined chunker: splits a byte stream into variable-length chunks at
+/// data-dependent boundaries (rather than fixed offsets), so that inserting or removing bytes
+/// near the start of a stream doesn't shift every chunk boundary after it. This is what lets
+/// `put_blob` dedup chunks across similar-but-not-identical blobs.
+pub struct Chunker<R> {
+    inner: BufReader<R>,
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+}
+
+impl<R: io::Read> Chunker<R> {
+    pub fn new(inner: R) -> Chunker<R> {
+        Chunker::with_sizes(inner, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE)
+    }
+
+    pub fn with_sizes(inner: R, min_size: usize, avg_size: usize, max_size: usize) -> Chunker<R> {
+        Chunker {
+            inner: BufReader::new(inner),
+            min_size,
+            avg_size,
+            max_size,
+        }
+    }
+
+    /// Read and return the next chunk, or `None` once the stream is exhausted.
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut chunk = Vec::new();
+        let mut fp: u64 = 0;
+        let mut byte = [0u8; 1];
+
+        while chunk.len() < self.max_size {
+            if self.inner.read(&mut byte)? == 0 {
+                break;
+            }
+            chunk.push(byte[0]);
+            fp = (fp << 1).wrapping_add(GEAR[byte[0] as usize]);
+
+            if chunk.len() >= self.min_size {
+                let mask = if chunk.len() < self.avg_size {
+                    MASK_SMALL
+                } else {
+                    MASK_LARGE
+                };
+                if fp & mask == 0 {
+                    break;
+                }
+            }
+        }
+
+        if chunk.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(chunk))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunker_reassembles_to_original() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut chunker = Chunker::new(data.as_slice());
+
+        let mut reassembled = Vec::new();
+        while let Some(chunk) = chunker.next_chunk().unwrap() {
+            assert!(chunk.len() <= MAX_CHUNK_SIZE);
+            reassembled.extend(chunk);
+        }
+
+        assert_eq!(reassembled, data);
+    }
+
+    #[test]
+    fn test_chunker_empty_input_yields_no_chunks() {
+        let mut chunker = Chunker::new(&[][..]);
+        assert_eq!(chunker.next_chunk().unwrap(), None);
+    }
+
+    #[test]
+    fn test_chunker_deterministic_boundaries() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 7) as u8).collect();
+
+        let chunk_lens = |d: &[u8]| -> Vec<usize> {
+            let mut c = Chunker::new(d);
+            let mut lens = Vec::new();
+            while let Some(chunk) = c.next_chunk().unwrap() {
+                lens.push(chunk.len());
+            }
+            lens
+        };
+
+        assert_eq!(chunk_lens(&data), chunk_lens(&data));
+    }
+}
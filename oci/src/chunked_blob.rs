@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::Descriptor;
+
+/// One content-defined chunk of a logical blob, and the offset at which it begins in the
+/// original (unchunked) stream that was passed to `put_blob`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ChunkRef {
+    pub descriptor: Descriptor,
+    pub offset: u64,
+}
+
+/// The manifest `put_blob` stores for a logical blob once it has been split into
+/// content-defined chunks: the ordered list of chunks that reassemble it. This is itself stored
+/// as a blob, and its own `Descriptor` is what `put_blob` returns to the caller.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct ChunkedBlob {
+    pub chunks: Vec<ChunkRef>,
+}
+
+// a fixed prefix identifying a blob as a chunk manifest, so a leaf chunk whose raw bytes happen
+// to parse as valid JSON in this same shape (e.g. a chunk that is literally `{"chunks":[]}`)
+// can't be mistaken for one: manifests always carry this tag, and nothing else ever does.
+const MAGIC: &[u8] = b"puzzlefs.chunked.v1\0";
+
+impl ChunkedBlob {
+    /// The chunk containing logical `offset`, and the offset within that chunk.
+    pub fn locate(&self, offset: u64) -> Option<(&ChunkRef, u64)> {
+        self.chunks
+            .iter()
+            .find(|c| offset < c.offset + c.descriptor.size())
+            .map(|c| (c, offset - c.offset))
+    }
+
+    /// Serializes `self` tagged with `MAGIC`, so `from_tagged_bytes` can later tell it apart
+    /// from an arbitrary leaf chunk's raw content.
+    pub fn to_tagged_bytes(&self) -> serde_json::Result<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        serde_json::to_writer(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Parses `bytes` as a chunk manifest iff it carries the `MAGIC` tag `to_tagged_bytes`
+    /// writes. Returns `None` for anything else, e.g. a leaf chunk's raw content.
+    pub fn from_tagged_bytes(bytes: &[u8]) -> Option<ChunkedBlob> {
+        serde_json::from_slice(bytes.strip_prefix(MAGIC)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Digest;
+
+    fn chunk_ref(offset: u64, size: u64) -> ChunkRef {
+        ChunkRef {
+            descriptor: Descriptor::new(Digest::new("sha256", "ab".repeat(32)), size),
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_locate_finds_containing_chunk() {
+        let manifest = ChunkedBlob {
+            chunks: vec![chunk_ref(0, 10), chunk_ref(10, 5)],
+        };
+
+        let (c, inner_offset) = manifest.locate(12).unwrap();
+        assert_eq!(c.offset, 10);
+        assert_eq!(inner_offset, 2);
+    }
+
+    #[test]
+    fn test_locate_past_end_is_none() {
+        let manifest = ChunkedBlob {
+            chunks: vec![chunk_ref(0, 10)],
+        };
+        assert!(manifest.locate(10).is_none());
+    }
+
+    #[test]
+    fn test_tagged_bytes_round_trip() {
+        let manifest = ChunkedBlob {
+            chunks: vec![chunk_ref(0, 10)],
+        };
+        let bytes = manifest.to_tagged_bytes().unwrap();
+        assert_eq!(ChunkedBlob::from_tagged_bytes(&bytes).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_from_tagged_bytes_rejects_untagged_lookalike() {
+        // a leaf chunk whose raw content happens to be valid JSON in the same shape as an empty
+        // manifest must not be mistaken for one, since it carries no tag.
+        assert!(ChunkedBlob::from_tagged_bytes(br#"{"chunks":[]}"#).is_none());
+    }
+}
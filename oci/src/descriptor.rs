@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use crate::digest::Digest;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Descriptor {
+    digest: Digest,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "mediaType", skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+}
+
+impl Descriptor {
+    pub fn new(digest: Digest, size: u64) -> Descriptor {
+        Descriptor {
+            digest,
+            size,
+            name: None,
+            media_type: None,
+        }
+    }
+
+    // the canonical `algorithm:encoded` form, e.g. what a registry expects in a manifest's
+    // `digest` field.
+    pub fn digest_as_str(&self) -> String {
+        self.digest.to_string()
+    }
+
+    pub fn digest(&self) -> &Digest {
+        &self.digest
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = Some(name)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set_media_type(&mut self, media_type: String) {
+        self.media_type = Some(media_type)
+    }
+
+    pub fn media_type(&self) -> Option<&str> {
+        self.media_type.as_deref()
+    }
+}
+
+impl From<Descriptor> for Digest {
+    fn from(d: Descriptor) -> Self {
+        d.digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_round_trips_digest() {
+        let digest = Digest::new("sha256", "ab".repeat(32));
+        let desc = Descriptor::new(digest.clone(), 42);
+        assert_eq!(desc.digest_as_str(), digest.to_string());
+        assert_eq!(desc.size(), 42);
+        assert_eq!(desc.name(), None);
+        assert_eq!(desc.media_type(), None);
+    }
+
+    #[test]
+    fn test_descriptor_round_trips_media_type() {
+        let mut desc = Descriptor::new(Digest::new("sha256", "ab".repeat(32)), 42);
+        desc.set_media_type(crate::media_type::ROOTFS_MEDIA_TYPE.to_string());
+        assert_eq!(desc.media_type(), Some(crate::media_type::ROOTFS_MEDIA_TYPE));
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The algorithm name of `sha2::Sha256`, and the one `fill_from_chunk` assumes for the raw
+/// 32-byte digest in a `format::BlobRef`.
+pub const SHA256: &str = "sha256";
+
+/// An extension point for the hash function `put_blob` uses to address a blob, so that images
+/// can eventually be built with sha512 or blake3 instead of the hardcoded sha256 of today.
+pub trait DigestAlgorithm: digest::Digest {
+    const NAME: &'static str;
+}
+
+impl DigestAlgorithm for sha2::Sha256 {
+    const NAME: &'static str = SHA256;
+}
+
+/// An OCI-spec digest of the form `<algorithm>:<encoded>` (e.g.
+/// `sha256:3abd5ce0f91f640d88dca1f26b37037b02415927cacec9626d87668a715ec12d`), per
+/// <https://github.com/opencontainers/image-spec/blob/main/descriptor.md#digests>.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: String,
+    encoded: String,
+}
+
+#[derive(Debug)]
+pub struct DigestParseError(String);
+
+impl fmt::Display for DigestParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid digest {:?}", self.0)
+    }
+}
+
+impl std::error::Error for DigestParseError {}
+
+fn is_algorithm_component(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+// algorithm ::= algorithm-component (algorithm-separator algorithm-component)*
+fn is_valid_algorithm(s: &str) -> bool {
+    s.split(['+', '.', '_', '-']).all(is_algorithm_component)
+}
+
+// encoded ::= [a-zA-Z0-9=_-]+
+fn is_valid_encoded(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '=' | '_' | '-'))
+}
+
+impl Digest {
+    pub fn new(algorithm: impl Into<String>, encoded: impl Into<String>) -> Digest {
+        Digest {
+            algorithm: algorithm.into(),
+            encoded: encoded.into(),
+        }
+    }
+
+    pub fn algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    pub fn encoded(&self) -> &str {
+        &self.encoded
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.encoded)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = DigestParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, encoded) = s
+            .split_once(':')
+            .ok_or_else(|| DigestParseError(s.to_string()))?;
+        if !is_valid_algorithm(algorithm) || !is_valid_encoded(encoded) {
+            return Err(DigestParseError(s.to_string()));
+        }
+        Ok(Digest::new(algorithm, encoded))
+    }
+}
+
+// serialize/deserialize through the canonical `algorithm:encoded` string so `Descriptor`s and
+// `Index` manifests round-trip exactly what a registry or `oci-layout` on disk expects.
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_round_trips_canonical_string() {
+        const S: &str = "sha256:3abd5ce0f91f640d88dca1f26b37037b02415927cacec9626d87668a715ec12d";
+        let d: Digest = S.parse().unwrap();
+        assert_eq!(d.algorithm(), "sha256");
+        assert_eq!(d.to_string(), S);
+    }
+
+    #[test]
+    fn test_digest_rejects_missing_separator() {
+        assert!("not-a-digest".parse::<Digest>().is_err());
+    }
+
+    #[test]
+    fn test_digest_rejects_invalid_encoded_chars() {
+        assert!("sha256:not valid!".parse::<Digest>().is_err());
+    }
+
+    #[test]
+    fn test_digest_serde_round_trip() {
+        let d = Digest::new("sha256", "deadbeef");
+        let json = serde_json::to_string(&d).unwrap();
+        assert_eq!(json, "\"sha256:deadbeef\"");
+        let back: Digest = serde_json::from_str(&json).unwrap();
+        assert_eq!(d, back);
+    }
+}
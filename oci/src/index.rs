@@ -1,26 +1,33 @@
 use std::collections::HashMap;
-use std::fs;
 use std::io;
-use std::path::Path;
 
+use cap_std::fs::Dir;
 use serde::{Deserialize, Serialize};
 
 extern crate serde_json;
 
 use crate::descriptor::Descriptor;
+use crate::media_type;
 
-// the OCI spec says this must be 2 in order for older dockers to use image layouts, and that it
-// will probably be removed. We could hard code it to two, but let's use -1 as an additional
-// indicator that this is a "weird" image. ...why is this defined as an int and not a uint? :)
-const PUZZLEFS_SCHEMA_VERSION: i32 = -1;
+// the OCI spec mandates this for older dockers to recognize an image layout.
+const OCI_SCHEMA_VERSION: i32 = 2;
 
 // the name of the index file as defined by the OCI spec
 pub const PATH: &str = "index.json";
 
+// the shape of an OCI artifact manifest: a `config` plus a list of typed `manifests`
+// (PuzzleFS's layers), each carrying its own `mediaType` so generic OCI tooling can store and
+// route these without understanding PuzzleFS itself.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Index {
     #[serde(rename = "schemaVersion")]
     version: i32,
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    #[serde(rename = "artifactType")]
+    artifact_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<Descriptor>,
     pub manifests: Vec<Descriptor>,
     pub annotations: HashMap<String, String>,
 }
@@ -28,7 +35,10 @@ pub struct Index {
 impl Default for Index {
     fn default() -> Self {
         Index {
-            version: PUZZLEFS_SCHEMA_VERSION,
+            version: OCI_SCHEMA_VERSION,
+            media_type: media_type::MANIFEST_MEDIA_TYPE.to_string(),
+            artifact_type: media_type::ARTIFACT_TYPE.to_string(),
+            config: None,
             manifests: Vec::new(),
             annotations: HashMap::new(),
         }
@@ -36,21 +46,42 @@ impl Default for Index {
 }
 
 impl Index {
-    pub(crate) fn open(p: &Path) -> Result<Index, Box<dyn std::error::Error>> {
-        let index_file = fs::File::open(p)?;
+    pub fn set_config(&mut self, config: Descriptor) {
+        self.config = Some(config)
+    }
+
+    pub fn config(&self) -> Option<&Descriptor> {
+        self.config.as_ref()
+    }
+
+    pub(crate) fn open(dir: &Dir) -> Result<Index, Box<dyn std::error::Error>> {
+        let index_file = dir.open(PATH)?;
         let index = serde_json::from_reader::<_, Index>(index_file)?;
-        if index.version != PUZZLEFS_SCHEMA_VERSION {
+        index.validate()?;
+        Ok(index)
+    }
+
+    /// Checks that `self` carries the schema version and media type this crate writes and
+    /// expects to read back, e.g. after parsing a manifest fetched from elsewhere (a registry)
+    /// rather than read via `open`.
+    pub(crate) fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.version != OCI_SCHEMA_VERSION {
             Err(Box::new(io::Error::new(
                 io::ErrorKind::Other,
-                format!("bad schema version {}", index.version),
+                format!("bad schema version {}", self.version),
+            )))
+        } else if self.media_type != media_type::MANIFEST_MEDIA_TYPE {
+            Err(Box::new(io::Error::new(
+                io::ErrorKind::Other,
+                format!("unrecognized manifest media type {}", self.media_type),
             )))
         } else {
-            Ok(index)
+            Ok(())
         }
     }
 
-    pub(crate) fn write(&self, p: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        let index_file = fs::File::create(p)?;
+    pub(crate) fn write(&self, dir: &Dir) -> Result<(), Box<dyn std::error::Error>> {
+        let index_file = dir.create(PATH)?;
         serde_json::to_writer(index_file, &self)?;
         Ok(())
     }
@@ -59,13 +90,25 @@ impl Index {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cap_std::ambient_authority;
     use tempfile::tempdir;
 
     #[test]
     fn test_can_open_new_index() {
-        let dir = tempdir().unwrap();
+        let tempdir = tempdir().unwrap();
+        let dir = Dir::open_ambient_dir(tempdir.path(), ambient_authority()).unwrap();
         let i = Index::default();
-        i.write(&dir.path().join(PATH)).unwrap();
-        Index::open(&dir.path().join(PATH)).unwrap();
+        i.write(&dir).unwrap();
+        Index::open(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_unrecognized_media_type() {
+        let tempdir = tempdir().unwrap();
+        let dir = Dir::open_ambient_dir(tempdir.path(), ambient_authority()).unwrap();
+        let mut i = Index::default();
+        i.media_type = "application/vnd.oci.image.index.v1+json".to_string();
+        i.write(&dir).unwrap();
+        assert!(Index::open(&dir).is_err());
     }
 }
@@ -0,0 +1,16 @@
+//! OCI Artifact media types for PuzzleFS images, per
+//! <https://github.com/opencontainers/image-spec/blob/main/manifest.md#oci-artifacts>. These let
+//! a registry or other OCI tooling tell a PuzzleFS image apart from an opaque layer blob.
+
+/// the rootfs (PuzzleFS metadata) blob's media type.
+pub const ROOTFS_MEDIA_TYPE: &str = "application/vnd.puzzlefs.image.rootfs.v1";
+
+/// the config blob's media type.
+pub const CONFIG_MEDIA_TYPE: &str = "application/vnd.puzzlefs.image.config.v1+json";
+
+/// the artifact manifest's own `mediaType`.
+pub const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.artifact.manifest.v1+json";
+
+/// the artifact manifest's `artifactType`, identifying this as a PuzzleFS image rather than an
+/// opaque OCI layer.
+pub const ARTIFACT_TYPE: &str = "application/vnd.puzzlefs.image.v1";